@@ -1,12 +1,16 @@
-use std::time::Duration;
-
-use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+use chrono::NaiveDate;
+use fastembed::{InitOptions, TextEmbedding};
+use fjall::Config;
+use jieba_rs::Jieba;
 use qdrant_client::{
     Qdrant,
-    qdrant::{SearchParamsBuilder, SearchPointsBuilder, point_id::PointIdOptions},
+    qdrant::{
+        Condition, Filter, Range, SearchParamsBuilder, SearchPointsBuilder,
+        point_id::PointIdOptions,
+    },
 };
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use vsearch::CONFIG;
+use vsearch::{CONFIG, KeywordIndex, normalize_scores, reciprocal_rank_fusion, resolve_model};
 
 #[tokio::main]
 async fn main() {
@@ -15,46 +19,146 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
     let client = Qdrant::from_url(&*CONFIG.qdrant_rpc).build().unwrap();
+
+    // Queries run against one named embedder from the registry; pick the
+    // first one configured so this stays in sync with the indexer by
+    // default instead of hardcoding a model that can drift from it.
+    let embedder = CONFIG
+        .embedders
+        .first()
+        .expect("CONFIG.embedders must not be empty");
     let mut model = TextEmbedding::try_new(
-        InitOptions::new(EmbeddingModel::BGESmallZHV15).with_show_download_progress(true),
+        InitOptions::new(resolve_model(&embedder.model)).with_show_download_progress(true),
     )
     .unwrap();
 
-    let query_str = vec!["北京动物保护"];
-    let query = model.embed(query_str, None).unwrap();
+    let keyspace = Config::new(CONFIG.db.as_str()).open().unwrap();
+    let keyword_index = KeywordIndex::open(&keyspace).unwrap();
+    let jieba = Jieba::new();
 
+    let query_str = "北京动物保护";
+    let query = model.embed(vec![query_str], None).unwrap();
     let query_vec = query[0].clone();
-    println!("{:?}", query_vec);
 
+    // e.g. only 北京市高级人民法院 judgments from 2018-2022, scoped server-side
+    // instead of filtering the (unfiltered) top-N client-side.
+    let filter = CaseFilter {
+        court: Some("北京市高级人民法院".into()),
+        year_range: Some((2018, 2022)),
+    }
+    .build();
+
+    let top_n = 30;
     let search_result = client
         .search_points(
-            SearchPointsBuilder::new(&*CONFIG.collection_name, query_vec, 30)
-                .with_payload(false)
+            SearchPointsBuilder::new(&*CONFIG.collection_name, query_vec, top_n as u64)
+                .with_payload(true)
+                .filter(filter)
+                .vector_name(&embedder.name)
                 .params(SearchParamsBuilder::default().exact(true)),
         )
         .await
         .unwrap();
 
-    for point in &search_result.result {
-        let id = point
-            .id
-            .as_ref()
-            .unwrap()
-            .point_id_options
-            .as_ref()
-            .unwrap();
-        match id {
-            PointIdOptions::Num(id_num) => {
-                let (case_id, chunk_id) = split_id(*id_num);
-                println!(
-                    "Point ID: {}, Case ID: {}, Chunk ID: {}",
-                    id_num, case_id, chunk_id
-                );
-            }
-            PointIdOptions::Uuid(uuid) => {
-                println!("Point UUID: {}", uuid);
+    // `search_result` is ranked best-first, and a case can appear under
+    // several chunk ids; keep only the first (best-scoring) hit per case.
+    let mut seen_cases = std::collections::HashSet::new();
+    let vector_ranked: Vec<(u32, f32)> = search_result
+        .result
+        .iter()
+        .filter_map(|point| {
+            let id = point.id.as_ref()?.point_id_options.as_ref()?;
+            match id {
+                PointIdOptions::Num(id_num) => Some((split_id(*id_num).0, point.score)),
+                PointIdOptions::Uuid(_) => None,
             }
+        })
+        .filter(|(case_id, _)| seen_cases.insert(*case_id))
+        .collect();
+
+    // The keyword index has no payload to filter by, so BM25 runs over the
+    // whole corpus; restricting its hits to `seen_cases` (the vector search's
+    // already-filtered result set) before fusion keeps filtered hybrid search
+    // from surfacing out-of-scope cases that only matched on keywords.
+    let bm25_ranked: Vec<(u32, f32)> = keyword_index
+        .bm25_search(&jieba, query_str, top_n)
+        .unwrap()
+        .into_iter()
+        .filter(|(case_id, _)| seen_cases.contains(case_id))
+        .collect();
+
+    let fused = fuse(&vector_ranked, &bm25_ranked);
+
+    for (case_id, score) in fused.into_iter().take(top_n) {
+        println!("Case ID: {}, Score: {:.4}", case_id, score);
+    }
+}
+
+/// Fuses the vector and BM25 rankings per `CONFIG.semantic_ratio`: weighted
+/// blending of normalized scores when it is set, Reciprocal Rank Fusion
+/// (`k = CONFIG.rrf_k`, default 60) otherwise.
+fn fuse(vector_ranked: &[(u32, f32)], bm25_ranked: &[(u32, f32)]) -> Vec<(u32, f32)> {
+    if let Some(ratio) = CONFIG.semantic_ratio {
+        let vector_norm = normalize_scores(vector_ranked);
+        let bm25_norm = normalize_scores(bm25_ranked);
+        let mut ids: Vec<u32> = vector_norm.keys().chain(bm25_norm.keys()).copied().collect();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let mut ranked: Vec<(u32, f32)> = ids
+            .into_iter()
+            .map(|id| {
+                let v = vector_norm.get(&id).copied().unwrap_or(0.0);
+                let b = bm25_norm.get(&id).copied().unwrap_or(0.0);
+                (id, ratio * v + (1.0 - ratio) * b)
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked
+    } else {
+        let k = CONFIG.rrf_k.unwrap_or(60.0);
+        let vector_ids: Vec<u32> = vector_ranked.iter().map(|(id, _)| *id).collect();
+        let bm25_ids: Vec<u32> = bm25_ranked.iter().map(|(id, _)| *id).collect();
+        reciprocal_rank_fusion(&[vector_ids, bm25_ids], k)
+    }
+}
+
+/// User-facing search constraints, translated into a Qdrant `Filter` over the
+/// payload fields `case_payload` writes during indexing.
+struct CaseFilter {
+    court: Option<String>,
+    year_range: Option<(i32, i32)>,
+}
+
+impl CaseFilter {
+    fn build(self) -> Filter {
+        let mut must = Vec::new();
+        if let Some(court) = self.court {
+            must.push(Condition::matches("court", court));
+        }
+        if let Some((from_year, to_year)) = self.year_range {
+            let gte = NaiveDate::from_ymd_opt(from_year, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_utc()
+                .timestamp();
+            let lte = NaiveDate::from_ymd_opt(to_year, 12, 31)
+                .unwrap()
+                .and_hms_opt(23, 59, 59)
+                .unwrap()
+                .and_utc()
+                .timestamp();
+            must.push(Condition::range(
+                "judgment_date_ts",
+                Range {
+                    gte: Some(gte as f64),
+                    lte: Some(lte as f64),
+                    ..Default::default()
+                },
+            ));
         }
+        Filter::must(must)
     }
 }
 
@@ -0,0 +1,42 @@
+use serde::Deserialize;
+use std::sync::LazyLock;
+
+use crate::embedder::{EmbedderConfig, default_embedders};
+
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    pub qdrant_rpc: String,
+    pub collection_name: String,
+    pub db: String,
+    pub batch_size: Option<usize>,
+    /// When set, hybrid search blends normalized vector/BM25 scores with this
+    /// weight (`ratio * vector + (1 - ratio) * bm25`) instead of fusing the
+    /// two rankings with Reciprocal Rank Fusion.
+    pub semantic_ratio: Option<f32>,
+    /// `k` constant for Reciprocal Rank Fusion; defaults to 60 if unset.
+    pub rrf_k: Option<f32>,
+    /// `{field}` template the indexer renders against `Case` to build the
+    /// embedding input; defaults to bare `{full_text}` if unset.
+    pub embedding_template: Option<String>,
+    /// When `true`, the indexer collapses each case's chunks into a single
+    /// length-weighted averaged vector instead of indexing one vector per
+    /// chunk. Defaults to `false` (per-chunk).
+    pub per_case_vector: Option<bool>,
+    /// Named embedder registry: which fastembed model writes into which
+    /// named vector on the collection. Falls back to a single BGE-small-zh
+    /// embedder named `"default"` when unset.
+    #[serde(default = "default_embedders")]
+    pub embedders: Vec<EmbedderConfig>,
+    /// Bypasses content fingerprinting and re-embeds every case, even ones
+    /// whose rendered text is unchanged. Also settable with `--force`.
+    pub force_reembed: Option<bool>,
+}
+
+pub static CONFIG: LazyLock<Config> = LazyLock::new(|| {
+    let settings = config::Config::builder()
+        .add_source(config::File::with_name("config"))
+        .add_source(config::Environment::with_prefix("VSEARCH"))
+        .build()
+        .expect("failed to load config");
+    settings.try_deserialize().expect("invalid config")
+});
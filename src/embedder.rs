@@ -0,0 +1,124 @@
+use std::collections::{HashMap, HashSet};
+
+use fastembed::EmbeddingModel;
+use qdrant_client::{
+    Qdrant,
+    qdrant::{
+        CreateCollectionBuilder, Distance, VectorParamsBuilder, VectorParamsMap, VectorsConfig,
+        vectors_config::Config as VectorsConfigOneOf,
+    },
+};
+use serde::Deserialize;
+
+/// One named embedder: a fastembed model plus the named vector it writes
+/// into. A collection can host several of these side by side so users can
+/// A/B different Chinese embedding models without recompiling, and so the
+/// indexer and query binary can't silently drift apart on model choice.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbedderConfig {
+    pub name: String,
+    pub model: String,
+    pub dimension: u64,
+    #[serde(default = "default_distance")]
+    pub distance: String,
+}
+
+fn default_distance() -> String {
+    "Cosine".to_string()
+}
+
+/// Falls back to the historical single BGE-small-zh model when `embedders`
+/// is absent from the config, so existing deployments keep working. An
+/// explicitly empty list is kept as it is.
+pub fn default_embedders() -> Vec<EmbedderConfig> {
+    vec![EmbedderConfig {
+        name: "default".to_string(),
+        model: "bge-small-zh-v1.5".to_string(),
+        dimension: 512,
+        distance: default_distance(),
+    }]
+}
+
+/// Resolves a config-level model name to the fastembed enum variant, so a
+/// typo surfaces at startup rather than as a silent dimension mismatch.
+pub fn resolve_model(name: &str) -> EmbeddingModel {
+    match name {
+        "bge-small-zh-v1.5" => EmbeddingModel::BGESmallZHV15,
+        "bge-base-zh-v1.5" => EmbeddingModel::BGEBaseZHV15,
+        "bge-large-zh-v1.5" => EmbeddingModel::BGELargeZHV15,
+        other => panic!("unknown embedder model {other:?}; add it to resolve_model"),
+    }
+}
+
+fn parse_distance(name: &str) -> Distance {
+    match name {
+        "Cosine" => Distance::Cosine,
+        "Dot" => Distance::Dot,
+        "Euclid" => Distance::Euclid,
+        "Manhattan" => Distance::Manhattan,
+        other => panic!("unknown distance metric {other:?}"),
+    }
+}
+
+/// Creates the collection with one named vector per configured embedder if
+/// it doesn't already exist, so the schema always matches `CONFIG.embedders`.
+/// If the collection already exists, reconciles it against the registry:
+/// Qdrant has no way to add a brand-new named vector to a collection after
+/// creation, so rather than silently indexing into a vector nobody declared,
+/// this panics with the missing embedder names and what to do about them.
+pub async fn ensure_collection(client: &Qdrant, collection_name: &str, embedders: &[EmbedderConfig]) {
+    if !client.collection_exists(collection_name).await.unwrap() {
+        let mut map = HashMap::new();
+        for e in embedders {
+            map.insert(
+                e.name.clone(),
+                VectorParamsBuilder::new(e.dimension, parse_distance(&e.distance)).build(),
+            );
+        }
+        let vectors_config = VectorsConfig {
+            config: Some(VectorsConfigOneOf::ParamsMap(VectorParamsMap { map })),
+        };
+
+        client
+            .create_collection(
+                CreateCollectionBuilder::new(collection_name).vectors_config(vectors_config),
+            )
+            .await
+            .unwrap();
+        return;
+    }
+
+    let existing = existing_vector_names(client, collection_name).await;
+    let missing: Vec<&str> = embedders
+        .iter()
+        .map(|e| e.name.as_str())
+        .filter(|name| !existing.contains(*name))
+        .collect();
+
+    if !missing.is_empty() {
+        panic!(
+            "collection {collection_name:?} exists but has no named vector for embedder(s) {missing:?}; \
+             Qdrant can't add a new named vector to an existing collection, so either drop these embedders \
+             from CONFIG.embedders or point collection_name at a fresh collection and re-index into it"
+        );
+    }
+}
+
+/// The named vectors actually declared on an existing collection. A
+/// collection created before named vectors existed has a single *unnamed*
+/// vector instead of a map;
+/// that layout is what `"default"` always meant before this registry existed,
+/// so it's reported as satisfying that one name rather than none.
+async fn existing_vector_names(client: &Qdrant, collection_name: &str) -> HashSet<String> {
+    let info = client.collection_info(collection_name).await.unwrap();
+    info.result
+        .and_then(|r| r.config)
+        .and_then(|c| c.params)
+        .and_then(|p| p.vectors_config)
+        .and_then(|v| v.config)
+        .map(|config| match config {
+            VectorsConfigOneOf::Params(_) => HashSet::from(["default".to_string()]),
+            VectorsConfigOneOf::ParamsMap(map) => map.map.keys().cloned().collect(),
+        })
+        .unwrap_or_default()
+}
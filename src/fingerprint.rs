@@ -0,0 +1,43 @@
+use bincode::config::standard;
+use bincode::{Decode, Encode};
+use fjall::{Keyspace, Partition, PartitionCreateOptions};
+
+#[derive(Debug, Clone, Copy, Encode, Decode)]
+pub struct Fingerprint {
+    pub hash: [u8; 32],
+    pub chunk_count: u32,
+}
+
+/// Per-case content fingerprints, so re-indexing after a template change only
+/// re-embeds cases whose rendered text actually changed instead of the whole
+/// corpus. The hash covers the rendered text alone, so a chunker or model
+/// change leaves every fingerprint as it was.
+pub struct FingerprintStore {
+    partition: Partition,
+}
+
+impl FingerprintStore {
+    pub fn open(keyspace: &Keyspace) -> fjall::Result<Self> {
+        let partition =
+            keyspace.open_partition("fingerprints", PartitionCreateOptions::default())?;
+        Ok(Self { partition })
+    }
+
+    pub fn get(&self, case_id: u32) -> fjall::Result<Option<Fingerprint>> {
+        Ok(self
+            .partition
+            .get(case_id.to_be_bytes())?
+            .map(|v| bincode::decode_from_slice(&v, standard()).unwrap().0))
+    }
+
+    pub fn set(&self, case_id: u32, fingerprint: Fingerprint) -> fjall::Result<()> {
+        let encoded = bincode::encode_to_vec(fingerprint, standard()).unwrap();
+        self.partition.insert(case_id.to_be_bytes(), encoded)
+    }
+}
+
+/// Hashes rendered embedding text with blake3 for cheap, content-addressed
+/// change detection.
+pub fn hash_text(text: &str) -> [u8; 32] {
+    *blake3::hash(text.as_bytes()).as_bytes()
+}
@@ -0,0 +1,215 @@
+use bincode::config::standard;
+use bincode::{Decode, Encode};
+use fjall::{Keyspace, Partition, PartitionCreateOptions};
+use jieba_rs::Jieba;
+use std::collections::HashMap;
+
+use crate::kv_sep_partition_option;
+
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+
+#[derive(Debug, Clone, Copy, Encode, Decode)]
+pub struct Posting {
+    pub case_id: u32,
+    pub term_freq: u32,
+}
+
+/// Inverted index over Chinese keyword tokens, backed by dedicated fjall
+/// partitions: postings list per token, token count per case, and index-wide
+/// stats (doc count, total tokens) needed for BM25's idf/avgdl terms.
+pub struct KeywordIndex {
+    postings: Partition,
+    doc_lengths: Partition,
+    doc_terms: Partition,
+    meta: Partition,
+}
+
+impl KeywordIndex {
+    pub fn open(keyspace: &Keyspace) -> fjall::Result<Self> {
+        let postings = keyspace.open_partition("keyword_postings", kv_sep_partition_option())?;
+        let doc_lengths =
+            keyspace.open_partition("keyword_doc_lengths", PartitionCreateOptions::default())?;
+        let doc_terms =
+            keyspace.open_partition("keyword_doc_terms", kv_sep_partition_option())?;
+        let meta = keyspace.open_partition("keyword_meta", PartitionCreateOptions::default())?;
+        Ok(Self {
+            postings,
+            doc_lengths,
+            doc_terms,
+            meta,
+        })
+    }
+
+    /// Segments `text` with jieba's search-oriented cut, dropping tokens with
+    /// no letter or digit in them (pure punctuation, ASCII or Chinese, symbols
+    /// or whitespace).
+    pub fn tokenize(jieba: &Jieba, text: &str) -> Vec<String> {
+        jieba
+            .cut_for_search(text, false)
+            .into_iter()
+            .map(|t| t.trim().to_string())
+            .filter(|t| t.chars().any(char::is_alphanumeric))
+            .collect()
+    }
+
+    /// Indexes one case's already-tokenized text, replacing any postings and
+    /// stats it previously contributed (so re-indexing a changed case
+    /// doesn't duplicate it or leave stale postings/`avgdl` skew behind).
+    pub fn index_case(&self, case_id: u32, tokens: &[String]) -> fjall::Result<()> {
+        let previous_length = self.raw_doc_length(case_id)?;
+        let previous_terms = self.get_doc_terms(case_id)?;
+
+        let mut term_freq: HashMap<&str, u32> = HashMap::new();
+        for t in tokens {
+            *term_freq.entry(t.as_str()).or_default() += 1;
+        }
+
+        // Drop this case from postings of terms it no longer contains; terms
+        // still present are rewritten below.
+        for term in &previous_terms {
+            if !term_freq.contains_key(term.as_str()) {
+                let mut list = self.get_postings(term)?;
+                list.retain(|p| p.case_id != case_id);
+                let encoded = bincode::encode_to_vec(&list, standard()).unwrap();
+                self.postings.insert(term.as_bytes(), encoded)?;
+            }
+        }
+
+        for (term, freq) in &term_freq {
+            let mut list = self.get_postings(term)?;
+            list.retain(|p| p.case_id != case_id);
+            list.push(Posting {
+                case_id,
+                term_freq: *freq,
+            });
+            let encoded = bincode::encode_to_vec(&list, standard()).unwrap();
+            self.postings.insert(term.as_bytes(), encoded)?;
+        }
+
+        let new_terms: Vec<String> = term_freq.keys().map(|t| t.to_string()).collect();
+        let encoded_terms = bincode::encode_to_vec(&new_terms, standard()).unwrap();
+        self.doc_terms.insert(case_id.to_be_bytes(), encoded_terms)?;
+
+        self.doc_lengths
+            .insert(case_id.to_be_bytes(), (tokens.len() as u32).to_be_bytes())?;
+
+        if previous_length.is_none() {
+            let doc_count = self.get_u64(b"doc_count")? + 1;
+            self.meta.insert(b"doc_count", doc_count.to_be_bytes())?;
+        }
+        let total_tokens = self.get_u64(b"total_tokens")? - previous_length.unwrap_or(0) as u64
+            + tokens.len() as u64;
+        self.meta
+            .insert(b"total_tokens", total_tokens.to_be_bytes())?;
+        Ok(())
+    }
+
+    fn get_u64(&self, key: &[u8]) -> fjall::Result<u64> {
+        Ok(self
+            .meta
+            .get(key)?
+            .map(|v| u64::from_be_bytes(v[..].try_into().unwrap()))
+            .unwrap_or(0))
+    }
+
+    fn get_postings(&self, term: &str) -> fjall::Result<Vec<Posting>> {
+        Ok(self
+            .postings
+            .get(term.as_bytes())?
+            .map(|v| bincode::decode_from_slice(&v, standard()).unwrap().0)
+            .unwrap_or_default())
+    }
+
+    fn doc_length(&self, case_id: u32) -> fjall::Result<u32> {
+        Ok(self.raw_doc_length(case_id)?.unwrap_or(0))
+    }
+
+    /// Like `doc_length`, but `None` distinguishes "never indexed" from a
+    /// stored length of zero, so `index_case` can tell whether a case is new.
+    fn raw_doc_length(&self, case_id: u32) -> fjall::Result<Option<u32>> {
+        Ok(self
+            .doc_lengths
+            .get(case_id.to_be_bytes())?
+            .map(|v| u32::from_be_bytes(v[..].try_into().unwrap())))
+    }
+
+    fn get_doc_terms(&self, case_id: u32) -> fjall::Result<Vec<String>> {
+        Ok(self
+            .doc_terms
+            .get(case_id.to_be_bytes())?
+            .map(|v| bincode::decode_from_slice(&v, standard()).unwrap().0)
+            .unwrap_or_default())
+    }
+
+    /// Ranks case ids by Okapi BM25 score against `query`, highest first.
+    pub fn bm25_search(
+        &self,
+        jieba: &Jieba,
+        query: &str,
+        top_n: usize,
+    ) -> fjall::Result<Vec<(u32, f32)>> {
+        let doc_count = self.get_u64(b"doc_count")? as f32;
+        if doc_count == 0.0 {
+            return Ok(Vec::new());
+        }
+        let avgdl = self.get_u64(b"total_tokens")? as f32 / doc_count;
+
+        let mut scores: HashMap<u32, f32> = HashMap::new();
+        for term in Self::tokenize(jieba, query) {
+            let postings = self.get_postings(&term)?;
+            if postings.is_empty() {
+                continue;
+            }
+            let df = postings.len() as f32;
+            let idf = ((doc_count - df + 0.5) / (df + 0.5) + 1.0).ln();
+            for p in postings {
+                let dl = self.doc_length(p.case_id)? as f32;
+                let tf = p.term_freq as f32;
+                let denom = tf + BM25_K1 * (1.0 - BM25_B + BM25_B * dl / avgdl);
+                *scores.entry(p.case_id).or_default() += idf * (tf * (BM25_K1 + 1.0)) / denom;
+            }
+        }
+
+        let mut ranked: Vec<(u32, f32)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranked.truncate(top_n);
+        Ok(ranked)
+    }
+}
+
+/// Reciprocal Rank Fusion over several ranked id lists: `score(d) += 1 / (k +
+/// r)` for the 0-based rank `r` of `d` in each list, with documents absent
+/// from a list contributing nothing for it. `k = 60` is the usual default.
+pub fn reciprocal_rank_fusion(lists: &[Vec<u32>], k: f32) -> Vec<(u32, f32)> {
+    let mut scores: HashMap<u32, f32> = HashMap::new();
+    for list in lists {
+        for (r, id) in list.iter().enumerate() {
+            *scores.entry(*id).or_default() += 1.0 / (k + r as f32);
+        }
+    }
+    let mut ranked: Vec<(u32, f32)> = scores.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
+
+/// Min-max normalizes a score list into `[0, 1]` so vector and BM25 scores
+/// (which live on unrelated scales) can be blended.
+pub fn normalize_scores(scores: &[(u32, f32)]) -> HashMap<u32, f32> {
+    if scores.is_empty() {
+        return HashMap::new();
+    }
+    let min = scores.iter().map(|(_, s)| *s).fold(f32::INFINITY, f32::min);
+    let max = scores
+        .iter()
+        .map(|(_, s)| *s)
+        .fold(f32::NEG_INFINITY, f32::max);
+    let range = max - min;
+    scores
+        .iter()
+        .map(|(id, s)| {
+            let norm = if range > 0.0 { (s - min) / range } else { 1.0 };
+            (*id, norm)
+        })
+        .collect()
+}
@@ -1,10 +1,16 @@
 pub use config::CONFIG;
+pub use embedder::{EmbedderConfig, default_embedders, ensure_collection, resolve_model};
+pub use fingerprint::{Fingerprint, FingerprintStore, hash_text};
+pub use keyword::{KeywordIndex, Posting, normalize_scores, reciprocal_rank_fusion};
 
 use bincode::{Decode, Encode};
 use fjall::{KvSeparationOptions, PartitionCreateOptions};
 use serde::{Deserialize, Serialize};
 
 mod config;
+mod embedder;
+mod fingerprint;
+mod keyword;
 
 pub fn kv_sep_partition_option() -> PartitionCreateOptions {
     PartitionCreateOptions::default()
@@ -49,3 +55,36 @@ pub struct Case {
     #[serde(rename = "全文")]
     pub full_text: String,
 }
+
+impl Case {
+    /// Parses `judgment_date` (ISO `2023-05-01` or Chinese-style
+    /// `2023年5月1日`) into a Unix timestamp (seconds, UTC midnight), for use
+    /// as a numeric Qdrant payload field that supports range filtering.
+    pub fn judgment_timestamp(&self) -> Option<i64> {
+        let date = self.judgment_date.trim();
+        let parsed = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .or_else(|_| chrono::NaiveDate::parse_from_str(date, "%Y年%m月%d日"));
+        parsed
+            .ok()
+            .map(|d| d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp())
+    }
+
+    /// Resolves `{field}` placeholders in `template` against this case, so
+    /// the embedding input captures structured fields (charge type, court)
+    /// alongside the narrative text rather than `full_text` alone.
+    /// `full_text` is taken as a parameter since it's the already-cleaned
+    /// (HTML-stripped) body, not `self.full_text`. Empty fields fall back to
+    /// "未知" so the rendered template still reads sensibly.
+    pub fn render_template(&self, template: &str, full_text: &str) -> String {
+        let fallback = |s: &str| if s.trim().is_empty() { "未知" } else { s };
+        template
+            .replace("{case_name}", fallback(&self.case_name))
+            .replace("{cause}", fallback(&self.cause))
+            .replace("{court}", fallback(&self.court))
+            .replace("{case_type}", fallback(&self.case_type))
+            .replace("{procedure}", fallback(&self.procedure))
+            .replace("{judgment_date}", fallback(&self.judgment_date))
+            .replace("{case_id}", fallback(&self.case_id))
+            .replace("{full_text}", full_text)
+    }
+}
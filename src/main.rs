@@ -1,20 +1,41 @@
 use bincode::config::standard;
-use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+use fastembed::{InitOptions, TextEmbedding};
 use fjall::{Config, PartitionCreateOptions};
+use jieba_rs::Jieba;
 use qdrant_client::{
     Qdrant,
-    qdrant::{PointStruct, UpsertPointsBuilder},
+    qdrant::{DeletePointsBuilder, PointStruct, UpsertPointsBuilder},
 };
 use scraper::Html;
-use serde_json::Map;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use vsearch::{CONFIG, Case, kv_sep_partition_option};
+use vsearch::{
+    CONFIG, Case, Fingerprint, FingerprintStore, KeywordIndex, ensure_collection, hash_text,
+    kv_sep_partition_option, resolve_model,
+};
 
 // feature cuda
 #[cfg(feature = "cuda")]
 use ort::ep::{self, ArenaExtendStrategy};
 
+#[cfg(feature = "cuda")]
+fn execution_providers() -> Vec<ep::ExecutionProviderDispatch> {
+    let cuda_ep = ep::CUDA::default()
+        .with_tf32(true)
+        .with_memory_limit(
+            8 * 1024 * 1024 * 1024, // 8 GB
+        )
+        .with_arena_extend_strategy(ArenaExtendStrategy::SameAsRequested)
+        .build();
+    vec![cuda_ep]
+}
+#[cfg(not(feature = "cuda"))]
+fn execution_providers() -> Vec<fastembed::ExecutionProviderDispatch> {
+    vec![]
+}
+
 #[tokio::main]
 async fn main() {
     tracing_subscriber::registry()
@@ -26,26 +47,21 @@ async fn main() {
     let batch_size = CONFIG.batch_size.unwrap_or(64);
     info!("batch size: {}", batch_size);
 
-    #[cfg(feature = "cuda")]
-    let eps = {
-        let cuda_ep = ep::CUDA::default()
-            .with_tf32(true)
-            .with_memory_limit(
-                8 * 1024 * 1024 * 1024, // 8 GB
+    ensure_collection(&client, &CONFIG.collection_name, &CONFIG.embedders).await;
+
+    let mut embedders: Vec<(String, TextEmbedding)> = CONFIG
+        .embedders
+        .iter()
+        .map(|e| {
+            let model = TextEmbedding::try_new(
+                InitOptions::new(resolve_model(&e.model))
+                    .with_show_download_progress(true)
+                    .with_execution_providers(execution_providers()),
             )
-            .with_arena_extend_strategy(ArenaExtendStrategy::SameAsRequested)
-            .build();
-        vec![cuda_ep]
-    };
-    #[cfg(not(feature = "cuda"))]
-    let eps = vec![];
-
-    let mut model = TextEmbedding::try_new(
-        InitOptions::new(EmbeddingModel::BGESmallZHV15)
-            .with_show_download_progress(true)
-            .with_execution_providers(eps),
-    )
-    .unwrap();
+            .unwrap();
+            (e.name.clone(), model)
+        })
+        .collect();
 
     let keyspace = Config::new(CONFIG.db.as_str()).open().unwrap();
     let db = keyspace
@@ -54,29 +70,39 @@ async fn main() {
     let progress_ks = keyspace
         .open_partition("progress", PartitionCreateOptions::default())
         .unwrap();
+    let keyword_index = KeywordIndex::open(&keyspace).unwrap();
+    let fingerprints = FingerprintStore::open(&keyspace).unwrap();
+    let jieba = Jieba::new();
+
+    let per_case_vector = CONFIG.per_case_vector.unwrap_or(false);
+    let force = CONFIG.force_reembed.unwrap_or(false) || std::env::args().any(|a| a == "--force");
 
     let mut case_count = 0;
+    let mut skipped_count = 0;
     let mut batch = 0;
-    let mut lengths = Vec::with_capacity(batch_size);
     let mut documents = Vec::with_capacity(batch_size);
     let mut ids = Vec::with_capacity(batch_size);
+    let mut payloads = Vec::with_capacity(batch_size);
+    // Fingerprints (and the stale chunk ids they obsolete) for cases whose
+    // points are only staged in `documents`/`ids`/`payloads` above, not yet
+    // upserted; committed once the batch containing them actually lands.
+    let mut pending_fingerprints: Vec<(u32, Fingerprint)> = Vec::with_capacity(batch_size);
+    let mut pending_stale_ids: Vec<u64> = Vec::new();
     let now = std::time::Instant::now();
 
-    let progress = if let Some(b) = progress_ks.get("progress").unwrap() {
-        u32::from_be_bytes(b[..].try_into().unwrap())
-    } else {
-        0
-    };
-
+    // Note: there's no monotonic resume point here on purpose. Content
+    // fingerprinting (below) decides per-case whether to skip or re-embed,
+    // so a re-run after a template change can touch cases at any id, not
+    // just ones past whatever was last reached. The fingerprint hashes the
+    // rendered text only, so a chunker or model change is picked up only
+    // with `--force`. `progress_ks` still records the id of the last case
+    // at each per-chunk batch flush; nothing reads it back.
     for i in db.iter() {
         let (k, v) = i.unwrap();
         let id = u32::from_be_bytes(k[..].try_into().unwrap());
         if id % 10000 == 0 {
             info!("case count: {}, id: {}", case_count, id);
         }
-        if id <= progress {
-            continue;
-        }
 
         let (case, _): (Case, _) = bincode::decode_from_slice(&v, standard()).unwrap();
         if case.case_type != "刑事案件" {
@@ -88,27 +114,92 @@ async fn main() {
         case_count += 1;
 
         let full_text = remove_html_tags(&case.full_text);
-        let chunks = chunk_chinese_text_backward(&full_text, 512, 512);
 
-        if chunks.len() == 1 {
-            documents.extend(chunks);
-            ids.push(id as u64);
-        } else {
+        let template = CONFIG.embedding_template.as_deref().unwrap_or("{full_text}");
+        let embedding_text = case.render_template(template, &full_text);
+
+        let previous = fingerprints.get(id).unwrap();
+        let hash = hash_text(&embedding_text);
+        if !force
+            && previous
+                .as_ref()
+                .is_some_and(|fingerprint| fingerprint.hash == hash)
+        {
+            skipped_count += 1;
+            continue;
+        }
+
+        let keyword_text = format!("{}\n{}\n{}", case.case_name, case.cause, full_text);
+        let tokens = KeywordIndex::tokenize(&jieba, &keyword_text);
+        keyword_index.index_case(id, &tokens).unwrap();
+
+        let chunks = chunk_chinese_text_backward(&embedding_text, 512, 512);
+
+        if chunks.len() > 1 {
             info!("case {id} chunk len:{}", chunks.len());
-            let mut tmp = Vec::with_capacity(chunks.len());
-            for chunk in chunks {
-                lengths.push(chunk.len()); // 字符长度
-                tmp.push(chunk);
+        }
+
+        let chunk_count = if per_case_vector { 1 } else { chunks.len() as u32 };
+        let stale_ids: Vec<u64> = previous
+            .filter(|p| p.chunk_count > chunk_count)
+            .map(|p| {
+                (chunk_count..p.chunk_count)
+                    .map(|chunk_index| chunk_point_id(id, chunk_index))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let fingerprint = Fingerprint { hash, chunk_count };
+
+        if per_case_vector {
+            // One vector per case: embed this case's own chunks per embedder
+            // and collapse each into a single length-weighted, L2-normalized
+            // vector under chunk index 0. Uploaded synchronously, so the
+            // fingerprint can be committed right after it succeeds.
+            let chunk_lengths: Vec<usize> = chunks.iter().map(|c| c.len()).collect();
+            let mut vectors_by_name = Vec::with_capacity(embedders.len());
+            for (name, model) in embedders.iter_mut() {
+                let chunk_embeddings = model.embed(&chunks, None).unwrap();
+                let mut avg_embedding = length_weighted_mean(&chunk_embeddings, &chunk_lengths);
+                l2_normalize(&mut avg_embedding);
+                vectors_by_name.push((name.clone(), vec![avg_embedding]));
             }
-            let embeddings = model.embed(&documents, None).unwrap();
-            let mut avg_embedding = length_weighted_mean(&embeddings, &lengths);
-            l2_normalize(&mut avg_embedding);
-            upload_embeddings(vec![avg_embedding], &vec![id as u64], &client).await;
+            upload_points(
+                &[chunk_point_id(id, 0)],
+                vec![case_payload(&case)],
+                &vectors_by_name,
+                &client,
+            )
+            .await;
+            delete_stale_points(&client, stale_ids).await;
+            fingerprints.set(id, fingerprint).unwrap();
+        } else {
+            // One vector per chunk, addressable by a composite point id so a
+            // multi-chunk case's hits can be traced back to it and deduped.
+            // The upload is deferred to the next batch flush, so staging the
+            // fingerprint/stale ids here and committing them only once that
+            // flush's upsert has actually succeeded (below) keeps a
+            // kill/OOM between now and the flush from marking this case done
+            // while its points never reached Qdrant.
+            for (chunk_index, chunk) in chunks.into_iter().enumerate() {
+                documents.push(chunk);
+                ids.push(chunk_point_id(id, chunk_index as u32));
+                payloads.push(case_payload(&case));
+            }
+            pending_stale_ids.extend(stale_ids);
+            pending_fingerprints.push((id, fingerprint));
         }
 
         if documents.len() >= batch_size {
-            let embeddings = model.embed(&documents, Some(batch_size)).unwrap();
-            upload_embeddings(embeddings, &ids, &client).await;
+            let mut vectors_by_name = Vec::with_capacity(embedders.len());
+            for (name, model) in embedders.iter_mut() {
+                let embeddings = model.embed(&documents, Some(batch_size)).unwrap();
+                vectors_by_name.push((name.clone(), embeddings));
+            }
+            upload_points(&ids, std::mem::take(&mut payloads), &vectors_by_name, &client).await;
+            delete_stale_points(&client, std::mem::take(&mut pending_stale_ids)).await;
+            for (case_id, fingerprint) in pending_fingerprints.drain(..) {
+                fingerprints.set(case_id, fingerprint).unwrap();
+            }
 
             batch += 1;
             documents.clear();
@@ -122,8 +213,16 @@ async fn main() {
     }
 
     if !documents.is_empty() {
-        let embeddings = model.embed(&documents, Some(batch_size)).unwrap();
-        upload_embeddings(embeddings, &ids, &client).await;
+        let mut vectors_by_name = Vec::with_capacity(embedders.len());
+        for (name, model) in embedders.iter_mut() {
+            let embeddings = model.embed(&documents, Some(batch_size)).unwrap();
+            vectors_by_name.push((name.clone(), embeddings));
+        }
+        upload_points(&ids, std::mem::take(&mut payloads), &vectors_by_name, &client).await;
+        delete_stale_points(&client, std::mem::take(&mut pending_stale_ids)).await;
+        for (case_id, fingerprint) in pending_fingerprints.drain(..) {
+            fingerprints.set(case_id, fingerprint).unwrap();
+        }
         batch += 1;
         documents.clear();
         ids.clear();
@@ -134,11 +233,30 @@ async fn main() {
     }
 
     info!(
-        "all done: case: {case_count}, time: {}",
+        "all done: case: {case_count}, skipped (unchanged): {skipped_count}, time: {}",
         now.elapsed().as_secs()
     );
 }
 
+/// Composite point id for a case's chunk: `(case_id as u64) << 32 |
+/// chunk_index`, matching the decode the query binary's `split_id` expects.
+fn chunk_point_id(case_id: u32, chunk_index: u32) -> u64 {
+    ((case_id as u64) << 32) | chunk_index as u64
+}
+
+/// Deletes chunk ids left over from a case shrinking (fewer chunks than its
+/// previous fingerprint recorded). No-op if `stale_ids` is empty, so callers
+/// can pass a drained buffer unconditionally.
+async fn delete_stale_points(client: &Qdrant, stale_ids: Vec<u64>) {
+    if stale_ids.is_empty() {
+        return;
+    }
+    client
+        .delete_points(DeletePointsBuilder::new(&*CONFIG.collection_name).points(stale_ids))
+        .await
+        .unwrap();
+}
+
 fn remove_html_tags(html: &str) -> String {
     let document = Html::parse_document(html);
     document
@@ -256,13 +374,40 @@ fn l2_normalize(v: &mut [f32]) {
     }
 }
 
-async fn upload_embeddings(embeddings: Vec<Vec<f32>>, ids: &Vec<u64>, client: &Qdrant) {
-    let mut points = Vec::with_capacity(embeddings.len());
-    for (i, embedding) in embeddings.into_iter().enumerate() {
-        let id = ids[i];
-        let object = Map::new();
-        let point = PointStruct::new(id, embedding, object);
-        points.push(point);
+/// Builds the Qdrant payload for a case: the fields legal retrieval actually
+/// filters on, rather than an opaque id. `judgment_date` is kept both as the
+/// original string and as a numeric `judgment_date_ts` for range filtering.
+fn case_payload(case: &Case) -> Map<String, Value> {
+    let mut payload = Map::new();
+    payload.insert("case_id".into(), case.case_id.clone().into());
+    payload.insert("court".into(), case.court.clone().into());
+    payload.insert("cause".into(), case.cause.clone().into());
+    payload.insert("case_type".into(), case.case_type.clone().into());
+    payload.insert("procedure".into(), case.procedure.clone().into());
+    payload.insert("judgment_date".into(), case.judgment_date.clone().into());
+    if let Some(ts) = case.judgment_timestamp() {
+        payload.insert("judgment_date_ts".into(), ts.into());
+    }
+    payload
+}
+
+/// Assembles one point per id with a named vector per configured embedder
+/// (`vectors_by_name[e].1[i]` is embedder `e`'s vector for `ids[i]`) and
+/// upserts the batch.
+async fn upload_points(
+    ids: &[u64],
+    payloads: Vec<Map<String, Value>>,
+    vectors_by_name: &[(String, Vec<Vec<f32>>)],
+    client: &Qdrant,
+) {
+    let mut points = Vec::with_capacity(ids.len());
+    for (i, &id) in ids.iter().enumerate() {
+        let mut vectors = HashMap::new();
+        for (name, embeddings) in vectors_by_name {
+            vectors.insert(name.clone(), embeddings[i].clone());
+        }
+        let payload = payloads.get(i).cloned().unwrap_or_default();
+        points.push(PointStruct::new(id, vectors, payload));
     }
     client
         .upsert_points(UpsertPointsBuilder::new(&*CONFIG.collection_name, points))